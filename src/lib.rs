@@ -1,5 +1,28 @@
-use openssl::symm::{Cipher, Crypter, Mode};
-use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr};
+mod scramble;
+
+#[cfg(feature = "openssl")]
+mod openssl_backend;
+#[cfg(feature = "aes")]
+mod rust_crypto_backend;
+
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
+use std::net::{AddrParseError, IpAddr};
+
+pub use scramble::{Encrypter, ScrambleCache, Scrambler};
+
+#[cfg(feature = "openssl")]
+pub use openssl_backend::OpensslAes128;
+#[cfg(feature = "aes")]
+pub use rust_crypto_backend::RustCryptoAes128;
+
+#[cfg(feature = "openssl")]
+type DefaultEncrypter = OpensslAes128;
+#[cfg(all(feature = "aes", not(feature = "openssl")))]
+type DefaultEncrypter = RustCryptoAes128;
+
+#[cfg(not(any(feature = "openssl", feature = "aes")))]
+compile_error!("cryptopan-rs requires the `openssl` or `aes` feature to be enabled");
 
 #[derive(Debug)]
 pub enum CryptoPAnError {
@@ -30,9 +53,6 @@ impl From<AddrParseError> for CryptoPAnError {
 #[derive(Debug)]
 pub enum CipherError {
     InvalidKeyLength(usize),
-    CipherCreationFailed,
-    EncryptionUpdateFailed,
-    EncryptionFinalizeFailed,
 }
 impl std::fmt::Display for CipherError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -42,124 +62,175 @@ impl std::fmt::Display for CipherError {
                 "Invalid key length (must be 32 bytes)\n Found {} bytes",
                 len
             ),
-            CipherError::CipherCreationFailed => write!(f, "Cipher creation failed"),
-            CipherError::EncryptionUpdateFailed => write!(f, "Encryption Update failed"),
-            CipherError::EncryptionFinalizeFailed => write!(f, "Encryption Finalize failed"),
         }
     }
 }
 
+/// Number of PBKDF2-HMAC-SHA256 iterations used by [`CryptoPAn::from_passphrase`].
+///
+/// Chosen in line with OWASP's current minimum recommendation for PBKDF2-HMAC-SHA256.
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// A CIDR range whose addresses are returned verbatim by [`CryptoPAn::anonymize`] instead of
+/// being anonymized, e.g. to keep a well-known block such as the IPv4 multicast range legible.
+#[derive(Debug, Clone, Copy)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// `prefix_len` is clamped to the bit width of `network`'s address family (32 for IPv4, 128
+    /// for IPv6): an out-of-range value is treated as matching the whole address, the same as an
+    /// exact `/32` or `/128` would.
+    pub fn new(network: IpAddr, prefix_len: u8) -> Self {
+        let max_prefix_len = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        Self {
+            network,
+            prefix_len: prefix_len.min(max_prefix_len),
+        }
+    }
+
+    fn contains(&self, addr: &IpAddr) -> bool {
+        if self.prefix_len == 0 {
+            // A `/0` matches every address of its family; shifting by the full width below
+            // would overflow.
+            return matches!(
+                (self.network, addr),
+                (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_))
+            );
+        }
+
+        match (self.network, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let shift = 32u32.saturating_sub(self.prefix_len as u32);
+                (u32::from(network) >> shift) == (u32::from(*addr) >> shift)
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let shift = 128u32.saturating_sub(self.prefix_len as u32);
+                (u128::from(network) >> shift) == (u128::from(*addr) >> shift)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Prefix-preserving IP address anonymizer, as described by the Crypto-PAn scheme.
+///
+/// This is a thin wrapper around [`Scrambler`] using the crate's default [`Encrypter`] backend.
+/// To pick a specific backend (or supply your own), use [`Scrambler`] directly.
+///
+/// `CryptoPAn` is `Send + Sync`, so a single instance can be wrapped in an [`Arc`][std::sync::Arc]
+/// and shared across a thread pool to anonymize in parallel without per-thread key setup. With
+/// the default `openssl` feature, encryptions still serialize behind a lock (see
+/// [`OpensslAes128`]), even if the `aes` feature is enabled as well. `CryptoPAn` uses
+/// `RustCryptoAes128`, which encrypts lock-free, only when `aes` is enabled and `openssl` is not.
+///
+/// [`OpensslAes128`]: crate::OpensslAes128
 pub struct CryptoPAn {
-    cipher: Crypter,
-    padding_int: u128,
+    scrambler: Scrambler<DefaultEncrypter>,
+    preserved_bits: usize,
+    passthrough: Vec<Cidr>,
 }
 
 impl CryptoPAn {
     pub fn new(key: &[u8]) -> Result<Self, CryptoPAnError> {
-        if key.len() != 32 {
-            return Err(CryptoPAnError::CipherError(CipherError::InvalidKeyLength(
-                key.len(),
-            )));
-        }
+        let key: &[u8; 32] = key
+            .try_into()
+            .map_err(|_| CipherError::InvalidKeyLength(key.len()))?;
+
+        Ok(Self {
+            scrambler: Scrambler::new(key),
+            preserved_bits: 0,
+            passthrough: Vec::new(),
+        })
+    }
 
-        // Prepare the AES cipher for encryption.
-        let mut cipher = Crypter::new(
-            Cipher::aes_128_ecb(),
-            Mode::Encrypt,
-            &key[..16], // First 16 bytes are the AES key.
-            None,       // ECB mode does not use an IV.
-        )
-        .map_err(|_| CipherError::CipherCreationFailed)?;
-        
-        // Disable padding from the crypter.
-        cipher.pad(false);
-        
-        // Correctly size the buffer for the output of the encryption operation.
-        // The AES block size is 16 bytes, so the output will also be 16 bytes.
-        let block_size = Cipher::aes_128_ecb().block_size();
-        let mut padding = vec![0; 16 + block_size]; // Output buffer sized to 16 bytes.
-
-        // Encrypt the second half of the key to use as padding.
-        // NOTE: `update` followed by `finalize` ensures complete encryption.
-        let mut cnt = cipher
-            .update(&key[16..], &mut padding)
-            .map_err(|_| CipherError::EncryptionUpdateFailed)?;
-        cnt += cipher
-            .finalize(&mut padding[cnt..])
-            .map_err(|_| CipherError::EncryptionFinalizeFailed)?;
-        padding.truncate(cnt);
+    /// Derives the 32-byte key from a passphrase and salt using PBKDF2-HMAC-SHA256, instead of
+    /// requiring the caller to supply raw key material.
+    ///
+    /// Two nodes given the same passphrase and salt always derive the same key, so their
+    /// anonymized output is directly comparable.
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Result<Self, CryptoPAnError> {
+        let mut key = [0u8; 32];
+        pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key);
 
         Ok(Self {
-            cipher,
-            padding_int: Self::to_int(&padding),
+            scrambler: Scrambler::new(&key),
+            preserved_bits: 0,
+            passthrough: Vec::new(),
         })
     }
 
-    fn to_int(byte_array: &[u8]) -> u128 {
-        // Convert a byte array to a u64 value.
-        byte_array
-            .iter()
-            .fold(0u128, |acc, &byte| (acc << 8) | u128::from(byte))
+    /// Leaves the first `bits` of every address unchanged, anonymizing only the rest, so a known
+    /// network portion (e.g. an internal RFC1918 `/16`) stays legible.
+    ///
+    /// `bits` is applied per address family rather than treated as a hard address width: it is
+    /// clamped to 32 bits for IPv4 addresses and 128 bits for IPv6 addresses. This lets a single
+    /// `CryptoPAn` anonymizing a mix of both families use a wider value (e.g. 64, to preserve an
+    /// IPv6 `/64`) without panicking on the IPv4 addresses it also handles.
+    pub fn set_preserved_bits(&mut self, bits: usize) {
+        self.preserved_bits = bits;
     }
 
-    fn to_array(&self, int_value: u128, int_value_len: usize) -> Vec<u8> {
-        // Convert a u64 value to a byte array.
-        let mut byte_array: Vec<u8> = Vec::with_capacity(int_value_len);
-        for i in 0..int_value_len {
-            byte_array.insert(0, ((int_value >> (i * 8)) & 0xff) as u8);
-        }
-        byte_array
+    /// Registers a CIDR range whose addresses are returned verbatim by [`CryptoPAn::anonymize`],
+    /// e.g. to never touch the IPv4 multicast block.
+    pub fn add_passthrough_cidr(&mut self, cidr: Cidr) {
+        self.passthrough.push(cidr);
     }
 
-    pub fn anonymize(&mut self, addr: &str) -> Result<IpAddr, CryptoPAnError> {
+    pub fn anonymize(&self, addr: &str) -> Result<IpAddr, CryptoPAnError> {
         let ip: IpAddr = addr.parse()?;
-        let (addr, version) = match ip {
-            IpAddr::V4(ipv4) => (u128::from(u32::from(ipv4)), 4),
-            IpAddr::V6(ipv6) => (u128::from(ipv6), 6),
-        };
 
-        let anonymized = self.anonymize_bin(addr, version)?;
+        if self.passthrough.iter().any(|cidr| cidr.contains(&ip)) {
+            return Ok(ip);
+        }
 
-        Ok(Self::format_ip(anonymized, version))
+        Ok(self
+            .scrambler
+            .scramble_ip_with_preserved(ip, self.preserved_bits))
     }
 
-    fn anonymize_bin(&mut self, addr: u128, version: u8) -> Result<u128, CryptoPAnError> {
-        let pos_max = if version == 4 { 32 } else { 128 };
-        let ext_addr = if version == 4 { addr << 96 } else { addr };
+    pub fn deanonymize(&self, addr: &str) -> Result<IpAddr, CryptoPAnError> {
+        let ip: IpAddr = addr.parse()?;
 
-        let mut flip_array = Vec::new();
-        for pos in 0..pos_max {
-            let mask = !0u128 >> pos;
-            let padded_addr = (self.padding_int & mask) | (ext_addr & !mask);
-            let padded_bytes = self.to_array(padded_addr, 16);
+        if self.passthrough.iter().any(|cidr| cidr.contains(&ip)) {
+            return Ok(ip);
+        }
 
-            let block_size = Cipher::aes_128_ecb().block_size();
-            let mut encrypted = vec![0u8; 16 + block_size];
-            let mut cnt = self
-                .cipher
-                .update(&padded_bytes, &mut encrypted)
-                .map_err(|_| CipherError::EncryptionUpdateFailed)?;
-            cnt += self
-                .cipher
-                .finalize(&mut encrypted[cnt..])
-                .map_err(|_| CipherError::EncryptionFinalizeFailed)?;
-            encrypted.truncate(cnt);
+        Ok(self
+            .scrambler
+            .unscramble_ip_with_preserved(ip, self.preserved_bits))
+    }
 
-            flip_array.push(encrypted[0] >> 7);
+    /// Equivalent to [`CryptoPAn::anonymize`], but reuses keystream bits cached in `cache` for
+    /// addresses that share a prefix with one already anonymized. Pass the same `cache` across
+    /// calls to amortize the cost of anonymizing datasets with long shared prefixes.
+    ///
+    /// Honors registered passthrough CIDRs and [`CryptoPAn::set_preserved_bits`]; the result is
+    /// bit-identical to [`CryptoPAn::anonymize`].
+    pub fn anonymize_ip_cached(&self, cache: &mut ScrambleCache, addr: IpAddr) -> IpAddr {
+        if self.passthrough.iter().any(|cidr| cidr.contains(&addr)) {
+            return addr;
         }
-        let result = flip_array
-            .into_iter()
-            .fold(0u128, |acc, x| (acc << 1) | (x as u128));
 
-        Ok(addr ^ result)
+        self.scrambler
+            .scramble_ip_cached_with_preserved(cache, addr, self.preserved_bits)
     }
 
-    fn format_ip(addr: u128, version: u8) -> IpAddr {
-        match version {
-            4 => IpAddr::V4(Ipv4Addr::from((addr & 0xFFFFFFFF) as u32)),
-            6 => IpAddr::V6(Ipv6Addr::from(addr)),
-            _ => unreachable!(),
-        }
+    /// Anonymizes many addresses, reusing keystream bits between addresses that share a prefix.
+    /// Real-world datasets often contain millions of addresses sharing long prefixes, so this is
+    /// considerably cheaper than calling [`CryptoPAn::anonymize`] once per address.
+    pub fn anonymize_many(&self, addrs: impl IntoIterator<Item = IpAddr>) -> Vec<IpAddr> {
+        let mut cache = ScrambleCache::new();
+        addrs
+            .into_iter()
+            .map(|addr| self.anonymize_ip_cached(&mut cache, addr))
+            .collect()
     }
 }
 
@@ -169,7 +240,7 @@ mod tests {
     use super::*;
     fn run_key_test(addr: &str, expected: &str) {
         // following key is the key used in the original crypto-pan source distribution code.
-        let mut cp = CryptoPAn::new(&[
+        let cp = CryptoPAn::new(&[
             21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
             131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
         ])
@@ -178,6 +249,17 @@ mod tests {
         assert_eq!(anonymized.to_string(), expected);
     }
 
+    fn run_deanonymize_test(addr: &str, anonymized: &str) {
+        // following key is the key used in the original crypto-pan source distribution code.
+        let cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        let original = cp.deanonymize(anonymized).unwrap();
+        assert_eq!(original.to_string(), addr);
+    }
+
     #[test]
     fn test_anonymize_ipv4_full_1() {
         run_key_test("128.11.68.132", "135.242.180.132");
@@ -552,4 +634,250 @@ mod tests {
     fn test_anonymize_ipv6_parcial5() {
         run_key_test("2001:db8::2", "4401:2bc:603f:d91d:27f:ff8e:e6f1:dc1c");
     }
+
+    #[test]
+    fn test_deanonymize_ipv4_full_1() {
+        run_deanonymize_test("128.11.68.132", "135.242.180.132");
+    }
+
+    #[test]
+    fn test_deanonymize_ipv4_full_2() {
+        run_deanonymize_test("192.102.249.13", "252.138.62.131");
+    }
+
+    #[test]
+    fn test_deanonymize_ipv4_full_3() {
+        run_deanonymize_test("216.148.237.145", "235.84.194.111");
+    }
+
+    #[test]
+    fn test_deanonymize_ipv6_parcial() {
+        run_deanonymize_test("::1", "78ff:f001:9fc0:20df:8380:b1f1:704:ed");
+    }
+
+    #[test]
+    fn test_deanonymize_ipv6_parcial4() {
+        run_deanonymize_test("2001:db8::1", "4401:2bc:603f:d91d:27f:ff8e:e6f1:dc1e");
+    }
+
+    #[test]
+    fn test_anonymize_deanonymize_roundtrip() {
+        let cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+
+        for addr in ["8.8.8.8", "10.0.0.1", "::1", "2001:db8::cafe"] {
+            let anonymized = cp.anonymize(addr).unwrap();
+            let original = cp.deanonymize(&anonymized.to_string()).unwrap();
+            assert_eq!(original.to_string(), addr);
+        }
+    }
+
+    #[test]
+    fn test_from_passphrase_is_deterministic() {
+        let cp1 = CryptoPAn::from_passphrase("correct horse battery staple", b"some-salt").unwrap();
+        let cp2 = CryptoPAn::from_passphrase("correct horse battery staple", b"some-salt").unwrap();
+
+        let anonymized1 = cp1.anonymize("192.0.2.1").unwrap();
+        let anonymized2 = cp2.anonymize("192.0.2.1").unwrap();
+        assert_eq!(anonymized1, anonymized2);
+    }
+
+    #[test]
+    fn test_anonymize_many_matches_individual() {
+        let cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+
+        let addrs = [
+            "128.11.68.132",
+            "128.11.68.133",
+            "129.118.74.4",
+            "::1",
+            "::2",
+            "2001:db8::1",
+        ];
+
+        let individually: Vec<_> = addrs
+            .iter()
+            .map(|addr| cp.anonymize(addr).unwrap())
+            .collect();
+        let batched = cp.anonymize_many(addrs.iter().map(|addr| addr.parse().unwrap()));
+
+        assert_eq!(individually, batched);
+    }
+
+    #[test]
+    fn test_anonymize_many_matches_individual_with_preserved_bits() {
+        let mut cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        cp.set_preserved_bits(16);
+
+        let addrs = ["128.11.68.132", "128.11.68.133", "129.118.74.4", "::1"];
+
+        let individually: Vec<_> = addrs
+            .iter()
+            .map(|addr| cp.anonymize(addr).unwrap())
+            .collect();
+        let batched = cp.anonymize_many(addrs.iter().map(|addr| addr.parse().unwrap()));
+
+        assert_eq!(individually, batched);
+    }
+
+    #[test]
+    fn test_from_passphrase_differs_by_salt() {
+        let cp1 = CryptoPAn::from_passphrase("correct horse battery staple", b"salt-a").unwrap();
+        let cp2 = CryptoPAn::from_passphrase("correct horse battery staple", b"salt-b").unwrap();
+
+        let anonymized1 = cp1.anonymize("192.0.2.1").unwrap();
+        let anonymized2 = cp2.anonymize("192.0.2.1").unwrap();
+        assert_ne!(anonymized1, anonymized2);
+    }
+
+    #[test]
+    fn test_preserved_bits_leaves_prefix_unchanged() {
+        let mut cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        cp.set_preserved_bits(16);
+
+        let anonymized = cp.anonymize("10.20.30.40").unwrap();
+        match anonymized {
+            IpAddr::V4(addr) => assert_eq!(&addr.octets()[..2], &[10, 20]),
+            IpAddr::V6(_) => panic!("expected an IPv4 address"),
+        }
+    }
+
+    #[test]
+    fn test_preserved_bits_wider_than_ipv4_does_not_panic() {
+        let mut cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        // 64 bits is a natural choice to preserve an IPv6 `/64`, but is wider than an IPv4
+        // address; it must clamp rather than panic when applied to one.
+        cp.set_preserved_bits(64);
+
+        let anonymized = cp.anonymize("10.20.30.40").unwrap();
+        match anonymized {
+            IpAddr::V4(addr) => assert_eq!(addr.octets(), [10, 20, 30, 40]),
+            IpAddr::V6(_) => panic!("expected an IPv4 address"),
+        }
+
+        let original = cp.deanonymize(&anonymized.to_string()).unwrap();
+        assert_eq!(original.to_string(), "10.20.30.40");
+    }
+
+    #[test]
+    fn test_preserved_bits_roundtrip() {
+        let mut cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        cp.set_preserved_bits(16);
+
+        let anonymized = cp.anonymize("10.20.30.40").unwrap();
+        let original = cp.deanonymize(&anonymized.to_string()).unwrap();
+        assert_eq!(original.to_string(), "10.20.30.40");
+    }
+
+    #[test]
+    fn test_passthrough_cidr_is_returned_verbatim() {
+        let mut cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        cp.add_passthrough_cidr(Cidr::new("224.0.0.0".parse().unwrap(), 4));
+
+        let anonymized = cp.anonymize("224.0.0.251").unwrap();
+        assert_eq!(anonymized.to_string(), "224.0.0.251");
+
+        // addresses outside the passthrough range are still anonymized.
+        let anonymized = cp.anonymize("192.0.2.1").unwrap();
+        assert_ne!(anonymized.to_string(), "192.0.2.1");
+    }
+
+    #[test]
+    fn test_passthrough_cidr_zero_prefix_matches_everything() {
+        let mut cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        cp.add_passthrough_cidr(Cidr::new("0.0.0.0".parse().unwrap(), 0));
+
+        let anonymized = cp.anonymize("192.0.2.1").unwrap();
+        assert_eq!(anonymized.to_string(), "192.0.2.1");
+
+        let original = cp.deanonymize("192.0.2.1").unwrap();
+        assert_eq!(original.to_string(), "192.0.2.1");
+    }
+
+    #[test]
+    fn test_passthrough_cidr_clamps_oversized_prefix_len() {
+        let mut cp = CryptoPAn::new(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ])
+        .unwrap();
+        // An oversized prefix_len (e.g. an IPv6-sized /128 mistakenly given to an IPv4 network)
+        // must clamp to the address family's width, so it matches only the exact address.
+        cp.add_passthrough_cidr(Cidr::new("192.0.2.1".parse().unwrap(), 128));
+
+        let anonymized = cp.anonymize("192.0.2.1").unwrap();
+        assert_eq!(anonymized.to_string(), "192.0.2.1");
+
+        // a different address is still anonymized: the clamp matched only the exact /32, not the
+        // whole address space.
+        let anonymized = cp.anonymize("192.0.2.2").unwrap();
+        assert_ne!(anonymized.to_string(), "192.0.2.2");
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_crypto_pan_is_send_and_sync() {
+        assert_send_sync::<CryptoPAn>();
+    }
+
+    #[test]
+    fn test_anonymize_from_multiple_threads() {
+        use std::sync::Arc;
+
+        let cp = Arc::new(
+            CryptoPAn::new(&[
+                21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152,
+                143, 131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+            ])
+            .unwrap(),
+        );
+
+        let handles: Vec<_> = ["128.11.68.132", "129.118.74.4", "192.102.249.13", "::1"]
+            .into_iter()
+            .map(|addr| {
+                let cp = Arc::clone(&cp);
+                std::thread::spawn(move || cp.anonymize(addr).unwrap())
+            })
+            .collect();
+
+        let from_threads: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        let sequential: Vec<_> = ["128.11.68.132", "129.118.74.4", "192.102.249.13", "::1"]
+            .into_iter()
+            .map(|addr| cp.anonymize(addr).unwrap())
+            .collect();
+
+        assert_eq!(from_threads, sequential);
+    }
 }
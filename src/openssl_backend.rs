@@ -0,0 +1,50 @@
+use crate::scramble::Encrypter;
+use openssl::symm::{Cipher, Crypter, Mode};
+use std::sync::Mutex;
+
+/// AES-128 [`Encrypter`] backed by OpenSSL.
+///
+/// This is the default backend. It requires linking against OpenSSL, which can be a problem for
+/// targets such as `wasm32` or fully static (e.g. musl) builds; see [`RustCryptoAes128`] for a
+/// dependency-light alternative.
+///
+/// The underlying `Crypter` is stateful, so it is kept behind a [`Mutex`] rather than a
+/// `RefCell`: this makes `OpensslAes128` (and, transitively, [`Scrambler`][crate::Scrambler] and
+/// [`CryptoPAn`][crate::CryptoPAn]) `Sync`, so a single instance can be shared across threads.
+/// That `Mutex` serializes every block encryption, though, so sharing one `OpensslAes128`-backed
+/// instance across a thread pool will not actually encrypt in parallel; it only avoids per-thread
+/// key setup. Threads that need to anonymize concurrently without contending on that lock should
+/// enable the `aes` feature and use [`RustCryptoAes128`] instead, which has no internal state to
+/// synchronize.
+///
+/// [`RustCryptoAes128`]: crate::RustCryptoAes128
+pub struct OpensslAes128(Mutex<Crypter>);
+
+impl Encrypter for OpensslAes128 {
+    fn from_key(key: &[u8; 16]) -> Self {
+        let mut cipher = Crypter::new(Cipher::aes_128_ecb(), Mode::Encrypt, key, None)
+            .expect("AES-128 cipher creation failed");
+
+        // Disable padding from the crypter: we only ever feed it whole 16-byte blocks.
+        cipher.pad(false);
+
+        Self(Mutex::new(cipher))
+    }
+
+    fn encrypt(&self, input: &[u8; 16]) -> [u8; 16] {
+        let mut cipher = self.0.lock().expect("AES-128 cipher mutex poisoned");
+        let block_size = Cipher::aes_128_ecb().block_size();
+        let mut out = vec![0u8; 16 + block_size];
+
+        let mut cnt = cipher
+            .update(input, &mut out)
+            .expect("AES-128 encryption update failed");
+        cnt += cipher
+            .finalize(&mut out[cnt..])
+            .expect("AES-128 encryption finalize failed");
+        out.truncate(cnt);
+
+        out.try_into()
+            .unwrap_or_else(|_| panic!("AES-128 must produce a 16-byte block"))
+    }
+}
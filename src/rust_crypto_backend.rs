@@ -0,0 +1,25 @@
+use crate::scramble::Encrypter;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+
+/// AES-128 [`Encrypter`] backed by the pure-Rust [`aes`](https://docs.rs/aes) crate.
+///
+/// Unlike [`OpensslAes128`], this backend does not link against OpenSSL, which makes it suitable
+/// for targets such as `wasm32` or fully static (e.g. musl) builds. Enable it with the `aes`
+/// feature.
+///
+/// [`OpensslAes128`]: crate::OpensslAes128
+pub struct RustCryptoAes128(Aes128);
+
+impl Encrypter for RustCryptoAes128 {
+    fn from_key(key: &[u8; 16]) -> Self {
+        Self(Aes128::new(GenericArray::from_slice(key)))
+    }
+
+    fn encrypt(&self, input: &[u8; 16]) -> [u8; 16] {
+        let mut block = GenericArray::clone_from_slice(input);
+        self.0.encrypt_block(&mut block);
+        block.into()
+    }
+}
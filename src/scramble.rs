@@ -27,8 +27,12 @@ where
 /// - **Efficient**: The encryption happens frequently during an anonymization\
 ///   (128 times per each IPv6 address) and needs to be reasonably fast.
 ///
+/// [`Scrambler`] and [`crate::CryptoPAn`] are `Send + Sync` so a single instance can be shared
+/// across threads (e.g. wrapped in an [`Arc`][std::sync::Arc]) for parallel anonymization. The
+/// `Send + Sync` supertrait bound below exists to guarantee that.
+///
 /// [block cipher]: https://en.wikipedia.org/wiki/Block_cipher
-pub trait Encrypter: Sized {
+pub trait Encrypter: Sized + Send + Sync {
     /// Initializes an [`Encrypter`] from a 128-bit key.
     fn from_key(key: &[u8; 16]) -> Self;
 
@@ -37,11 +41,42 @@ pub trait Encrypter: Sized {
     /// # Note
     ///
     /// Cipher implementations often require mutable access to its internal state during an
-    /// encryption. In these cases, [interior mutability][std::cell] such as `UnsafeCell` will have
-    /// to be used and the implementer must ensure that the output of this method is deterministic.
+    /// encryption. In these cases, interior mutability (e.g. a [`Mutex`][std::sync::Mutex]) will
+    /// have to be used and the implementer must ensure that the output of this method is
+    /// deterministic and that the chosen interior mutability keeps the type `Sync`.
     fn encrypt(&self, input: &[u8; 16]) -> [u8; 16];
 }
 
+/// A node in the [`ScrambleCache`] trie.
+///
+/// Each node corresponds to a bit position reached by following a specific prefix of
+/// already-scrambled addresses from the root. `keystream_bit` is the encrypted bit computed for
+/// that position the first time it was needed; `children` descends into the next bit, keyed by
+/// its value.
+#[derive(Default)]
+struct TrieNode {
+    keystream_bit: Option<u8>,
+    children: [Option<Box<TrieNode>>; 2],
+}
+
+/// Memoizes the per-position keystream bits computed by [`Scrambler::scramble_cached`], reused
+/// across addresses that share a common prefix.
+///
+/// Since a keystream bit at position `i` depends only on the first `i` bits of the address being
+/// scrambled (not on the address family or its remaining bits), a single cache can be shared
+/// across any mix of IPv4 and IPv6 addresses. Reuse one [`ScrambleCache`] across many calls to
+/// amortize the cost of anonymizing datasets with long shared prefixes.
+#[derive(Default)]
+pub struct ScrambleCache {
+    root: TrieNode,
+}
+
+impl ScrambleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
 pub struct Scrambler<E: Encrypter> {
     encrypter: E,
     padding: [u8; 16],
@@ -86,6 +121,208 @@ impl<E: Encrypter> Scrambler<E> {
         zip_with(bytes, &result, |b, r| b ^ r)
     }
 
+    /// Inverts [`Scrambler::scramble`], recovering the original bytes from a scrambled value.
+    ///
+    /// This requires the same `n_bits` that was used to scramble `bytes` in the first place.
+    /// Since `scramble`'s output bit at position `i` only depends on the original's first `i`
+    /// bits, the original can be rebuilt MSB-first: recover bit `i`, then use it (along with the
+    /// bits recovered so far) to compute bit `i + 1`.
+    pub fn unscramble(&self, bytes: &[u8; 16], n_bits: usize) -> [u8; 16] {
+        if n_bits > 128 {
+            panic!("`n_bits` should not exceed 128");
+        }
+
+        let mut mask: [u8; 16] = [0b11111111; 16];
+        let mut original: [u8; 16] = [0; 16];
+
+        for i in 0..n_bits {
+            // padded = (original & !mask) | (self.padding & mask)
+            // first `i` bits from the already-recovered `original`, the rest from `padding`
+            let padded = {
+                let orig = zip_with(&mask, &original, |m, o| !m & o);
+                let padding = zip_with(&mask, &self.padding, |m, p| m & p);
+                zip_with(&orig, &padding, |o, p| o | p)
+            };
+            let encrypted = self.encrypter.encrypt(&padded);
+
+            let byte = i / 8;
+            let shift = 7 - (i % 8);
+            let scrambled_bit = (bytes[byte] >> shift) & 1;
+            let original_bit = scrambled_bit ^ (encrypted[0] >> 7);
+
+            original[byte] |= original_bit << shift;
+            mask[byte] >>= 1;
+        }
+
+        original
+    }
+
+    /// Equivalent to [`Scrambler::scramble`], but memoizes the per-position keystream bits in
+    /// `cache` so addresses sharing a prefix with one already scrambled skip the cipher for every
+    /// bit of that shared prefix. The result is bit-identical to `scramble`.
+    pub fn scramble_cached(&self, cache: &mut ScrambleCache, bytes: &[u8; 16], n_bits: usize) -> [u8; 16] {
+        if n_bits > 128 {
+            panic!("`n_bits` should not exceed 128");
+        }
+
+        let mut mask: [u8; 16] = [0b11111111; 16];
+        let mut result: [u8; 16] = [0; 16];
+        let mut node = &mut cache.root;
+
+        for i in 0..n_bits {
+            let keystream_bit = *node.keystream_bit.get_or_insert_with(|| {
+                // padded = (bytes & !mask) | (self.padding & mask)
+                // first `i` bits from `bytes`, the rest from `padding`
+                let padded = {
+                    let bytes = zip_with(&mask, bytes, |m, b| !m & b);
+                    let padding = zip_with(&mask, &self.padding, |m, p| m & p);
+                    zip_with(&bytes, &padding, |b, p| b | p)
+                };
+                self.encrypter.encrypt(&padded)[0] >> 7
+            });
+
+            result[i / 8] = (result[i / 8] << 1) | keystream_bit;
+            mask[i / 8] >>= 1;
+
+            let bit = ((bytes[i / 8] >> (7 - i % 8)) & 1) as usize;
+            node = node.children[bit].get_or_insert_with(Box::default);
+        }
+
+        zip_with(bytes, &result, |b, r| b ^ r)
+    }
+
+    /// Equivalent to [`Scrambler::scramble_cached`], but forces the first `preserve_bits` result
+    /// bits to zero, as in [`Scrambler::scramble_with_preserved`]. The result is bit-identical to
+    /// `scramble_with_preserved`.
+    pub fn scramble_cached_with_preserved(
+        &self,
+        cache: &mut ScrambleCache,
+        bytes: &[u8; 16],
+        n_bits: usize,
+        preserve_bits: usize,
+    ) -> [u8; 16] {
+        if n_bits > 128 {
+            panic!("`n_bits` should not exceed 128");
+        }
+        if preserve_bits > n_bits {
+            panic!("`preserve_bits` should not exceed `n_bits`");
+        }
+
+        let mut mask: [u8; 16] = [0b11111111; 16];
+        let mut result: [u8; 16] = [0; 16];
+        let mut node = &mut cache.root;
+
+        for i in 0..n_bits {
+            let keystream_bit = if i < preserve_bits {
+                0
+            } else {
+                *node.keystream_bit.get_or_insert_with(|| {
+                    // padded = (bytes & !mask) | (self.padding & mask)
+                    // first `i` bits from `bytes`, the rest from `padding`
+                    let padded = {
+                        let bytes = zip_with(&mask, bytes, |m, b| !m & b);
+                        let padding = zip_with(&mask, &self.padding, |m, p| m & p);
+                        zip_with(&bytes, &padding, |b, p| b | p)
+                    };
+                    self.encrypter.encrypt(&padded)[0] >> 7
+                })
+            };
+
+            result[i / 8] = (result[i / 8] << 1) | keystream_bit;
+            mask[i / 8] >>= 1;
+
+            let bit = ((bytes[i / 8] >> (7 - i % 8)) & 1) as usize;
+            node = node.children[bit].get_or_insert_with(Box::default);
+        }
+
+        zip_with(bytes, &result, |b, r| b ^ r)
+    }
+
+    /// Equivalent to [`Scrambler::scramble`], but forces the first `preserve_bits` result bits to
+    /// zero so that many of the address' leading bits pass through unchanged, while the rest are
+    /// scrambled exactly as before. Useful to keep a known network portion (e.g. an internal
+    /// RFC1918 `/16`) legible while still anonymizing the rest of the address.
+    pub fn scramble_with_preserved(
+        &self,
+        bytes: &[u8; 16],
+        n_bits: usize,
+        preserve_bits: usize,
+    ) -> [u8; 16] {
+        if n_bits > 128 {
+            panic!("`n_bits` should not exceed 128");
+        }
+        if preserve_bits > n_bits {
+            panic!("`preserve_bits` should not exceed `n_bits`");
+        }
+
+        let mut mask: [u8; 16] = [0b11111111; 16];
+        let mut result: [u8; 16] = [0; 16];
+
+        for i in 0..n_bits {
+            let keystream_bit = if i < preserve_bits {
+                0
+            } else {
+                // padded = (bytes & !mask) | (self.padding & mask)
+                // first `i` bits from `bytes`, the rest from `padding`
+                let padded = {
+                    let bytes = zip_with(&mask, bytes, |m, b| !m & b);
+                    let padding = zip_with(&mask, &self.padding, |m, p| m & p);
+                    zip_with(&bytes, &padding, |b, p| b | p)
+                };
+                self.encrypter.encrypt(&padded)[0] >> 7
+            };
+
+            result[i / 8] = (result[i / 8] << 1) | keystream_bit;
+            mask[i / 8] >>= 1;
+        }
+
+        zip_with(bytes, &result, |b, r| b ^ r)
+    }
+
+    /// Inverts [`Scrambler::scramble_with_preserved`], recovering the original bytes from a value
+    /// that was scrambled with the same `preserve_bits`.
+    pub fn unscramble_with_preserved(
+        &self,
+        bytes: &[u8; 16],
+        n_bits: usize,
+        preserve_bits: usize,
+    ) -> [u8; 16] {
+        if n_bits > 128 {
+            panic!("`n_bits` should not exceed 128");
+        }
+        if preserve_bits > n_bits {
+            panic!("`preserve_bits` should not exceed `n_bits`");
+        }
+
+        let mut mask: [u8; 16] = [0b11111111; 16];
+        let mut original: [u8; 16] = [0; 16];
+
+        for i in 0..n_bits {
+            let byte = i / 8;
+            let shift = 7 - (i % 8);
+            let scrambled_bit = (bytes[byte] >> shift) & 1;
+
+            let original_bit = if i < preserve_bits {
+                // preserved bits pass through unchanged.
+                scrambled_bit
+            } else {
+                // padded = (original & !mask) | (self.padding & mask)
+                // first `i` bits from the already-recovered `original`, the rest from `padding`
+                let padded = {
+                    let orig = zip_with(&mask, &original, |m, o| !m & o);
+                    let padding = zip_with(&mask, &self.padding, |m, p| m & p);
+                    zip_with(&orig, &padding, |o, p| o | p)
+                };
+                scrambled_bit ^ (self.encrypter.encrypt(&padded)[0] >> 7)
+            };
+
+            original[byte] |= original_bit << shift;
+            mask[byte] >>= 1;
+        }
+
+        original
+    }
+
     pub fn scramble_ip(&self, addr: IpAddr) -> IpAddr {
         match addr {
             IpAddr::V4(addr) => self.scramble_ipv4(addr).into(),
@@ -107,4 +344,156 @@ impl<E: Encrypter> Scrambler<E> {
         let bytes = addr.octets();
         self.scramble(&bytes, 128).into()
     }
+
+    /// Equivalent to [`Scrambler::scramble_ip`], but reuses keystream bits cached in `cache`.
+    pub fn scramble_ip_cached(&self, cache: &mut ScrambleCache, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(addr) => self.scramble_ipv4_cached(cache, addr).into(),
+            IpAddr::V6(addr) => self.scramble_ipv6_cached(cache, addr).into(),
+        }
+    }
+
+    pub fn scramble_ipv4_cached(&self, cache: &mut ScrambleCache, addr: Ipv4Addr) -> Ipv4Addr {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&addr.octets());
+
+        let anonymized = self.scramble_cached(cache, &bytes, 32);
+        let truncated: [u8; 4] = anonymized[..4].try_into().unwrap();
+
+        truncated.into()
+    }
+
+    pub fn scramble_ipv6_cached(&self, cache: &mut ScrambleCache, addr: Ipv6Addr) -> Ipv6Addr {
+        let bytes = addr.octets();
+        self.scramble_cached(cache, &bytes, 128).into()
+    }
+
+    /// Equivalent to [`Scrambler::scramble_ip_cached`], but forces the first `preserve_bits`
+    /// result bits to zero, as in [`Scrambler::scramble_ip_with_preserved`].
+    pub fn scramble_ip_cached_with_preserved(
+        &self,
+        cache: &mut ScrambleCache,
+        addr: IpAddr,
+        preserve_bits: usize,
+    ) -> IpAddr {
+        match addr {
+            IpAddr::V4(addr) => self
+                .scramble_ipv4_cached_with_preserved(cache, addr, preserve_bits)
+                .into(),
+            IpAddr::V6(addr) => self
+                .scramble_ipv6_cached_with_preserved(cache, addr, preserve_bits)
+                .into(),
+        }
+    }
+
+    /// `preserve_bits` is clamped to 32, the bit width of an IPv4 address, so a `preserve_bits`
+    /// configured for wider IPv6 prefixes (e.g. a `/64`) does not panic when applied to an IPv4
+    /// address.
+    pub fn scramble_ipv4_cached_with_preserved(
+        &self,
+        cache: &mut ScrambleCache,
+        addr: Ipv4Addr,
+        preserve_bits: usize,
+    ) -> Ipv4Addr {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&addr.octets());
+
+        let anonymized =
+            self.scramble_cached_with_preserved(cache, &bytes, 32, preserve_bits.min(32));
+        let truncated: [u8; 4] = anonymized[..4].try_into().unwrap();
+
+        truncated.into()
+    }
+
+    /// `preserve_bits` is clamped to 128, the bit width of an IPv6 address.
+    pub fn scramble_ipv6_cached_with_preserved(
+        &self,
+        cache: &mut ScrambleCache,
+        addr: Ipv6Addr,
+        preserve_bits: usize,
+    ) -> Ipv6Addr {
+        let bytes = addr.octets();
+        self.scramble_cached_with_preserved(cache, &bytes, 128, preserve_bits.min(128))
+            .into()
+    }
+
+    /// Equivalent to [`Scrambler::scramble_ip`], but forces the first `preserve_bits` result bits
+    /// to zero, as in [`Scrambler::scramble_with_preserved`].
+    pub fn scramble_ip_with_preserved(&self, addr: IpAddr, preserve_bits: usize) -> IpAddr {
+        match addr {
+            IpAddr::V4(addr) => self.scramble_ipv4_with_preserved(addr, preserve_bits).into(),
+            IpAddr::V6(addr) => self.scramble_ipv6_with_preserved(addr, preserve_bits).into(),
+        }
+    }
+
+    /// `preserve_bits` is clamped to 32, the bit width of an IPv4 address, so a `preserve_bits`
+    /// configured for wider IPv6 prefixes (e.g. a `/64`) does not panic when applied to an IPv4
+    /// address.
+    pub fn scramble_ipv4_with_preserved(&self, addr: Ipv4Addr, preserve_bits: usize) -> Ipv4Addr {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&addr.octets());
+
+        let anonymized = self.scramble_with_preserved(&bytes, 32, preserve_bits.min(32));
+        let truncated: [u8; 4] = anonymized[..4].try_into().unwrap();
+
+        truncated.into()
+    }
+
+    /// `preserve_bits` is clamped to 128, the bit width of an IPv6 address.
+    pub fn scramble_ipv6_with_preserved(&self, addr: Ipv6Addr, preserve_bits: usize) -> Ipv6Addr {
+        let bytes = addr.octets();
+        self.scramble_with_preserved(&bytes, 128, preserve_bits.min(128))
+            .into()
+    }
+
+    /// Equivalent to [`Scrambler::unscramble_ip`], but inverts
+    /// [`Scrambler::scramble_ip_with_preserved`] for the same `preserve_bits`.
+    pub fn unscramble_ip_with_preserved(&self, addr: IpAddr, preserve_bits: usize) -> IpAddr {
+        match addr {
+            IpAddr::V4(addr) => self.unscramble_ipv4_with_preserved(addr, preserve_bits).into(),
+            IpAddr::V6(addr) => self.unscramble_ipv6_with_preserved(addr, preserve_bits).into(),
+        }
+    }
+
+    /// `preserve_bits` is clamped to 32, the bit width of an IPv4 address, so a `preserve_bits`
+    /// configured for wider IPv6 prefixes (e.g. a `/64`) does not panic when applied to an IPv4
+    /// address.
+    pub fn unscramble_ipv4_with_preserved(&self, addr: Ipv4Addr, preserve_bits: usize) -> Ipv4Addr {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&addr.octets());
+
+        let original = self.unscramble_with_preserved(&bytes, 32, preserve_bits.min(32));
+        let truncated: [u8; 4] = original[..4].try_into().unwrap();
+
+        truncated.into()
+    }
+
+    /// `preserve_bits` is clamped to 128, the bit width of an IPv6 address.
+    pub fn unscramble_ipv6_with_preserved(&self, addr: Ipv6Addr, preserve_bits: usize) -> Ipv6Addr {
+        let bytes = addr.octets();
+        self.unscramble_with_preserved(&bytes, 128, preserve_bits.min(128))
+            .into()
+    }
+
+    pub fn unscramble_ip(&self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(addr) => self.unscramble_ipv4(addr).into(),
+            IpAddr::V6(addr) => self.unscramble_ipv6(addr).into(),
+        }
+    }
+
+    pub fn unscramble_ipv4(&self, addr: Ipv4Addr) -> Ipv4Addr {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&addr.octets());
+
+        let original = self.unscramble(&bytes, 32);
+        let truncated: [u8; 4] = original[..4].try_into().unwrap();
+
+        truncated.into()
+    }
+
+    pub fn unscramble_ipv6(&self, addr: Ipv6Addr) -> Ipv6Addr {
+        let bytes = addr.octets();
+        self.unscramble(&bytes, 128).into()
+    }
 }